@@ -1,5 +1,12 @@
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufReader, BufRead, Error};
+use std::io::{self, BufReader, BufRead, Error, ErrorKind, IsTerminal};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use glob::glob;
+use rayon::prelude::*;
+use regex::Regex;
 
 /// While using `&[&str]` to handle flags is convenient for exercise purposes,
 /// and resembles the output of [`std::env::args`], in real-world projects it is
@@ -23,6 +30,58 @@ pub struct Flags{
     pub invert_match: bool,
     pub match_entire_line: bool,
     pub invert: bool,
+    // Should contain a flag -F Treat the pattern as a literal string rather than a regex.
+    pub fixed_string: bool,
+    // Should contain a flag -r Recurse into directories, searching every file beneath them.
+    pub recursive: bool,
+    // Should contain a flag -c Print only a count of matching lines per file.
+    pub count: bool,
+    // Should contain a flag -o Print only the matched part of each line, one match per line.
+    pub only_matching: bool,
+    // Should contain a flag -B N Print N lines of context before each match.
+    pub before: usize,
+    // Should contain a flag -A N Print N lines of context after each match.
+    pub after: usize,
+    // Should contain a flag --color WHEN Highlight matches with ANSI escapes.
+    pub color: ColorChoice,
+}
+
+/// When to emit ANSI color escapes, mirroring grep's `--color` argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color only when standard output is a terminal.
+    Auto,
+    /// Always color, even when piped or redirected.
+    Always,
+    /// Never color.
+    Never,
+}
+
+impl ColorChoice {
+    fn new(flags: &[&str]) -> Self {
+        match flags
+            .iter()
+            .position(|&flag| flag == "--color")
+            .and_then(|i| flags.get(i + 1))
+        {
+            Some(&"always") => ColorChoice::Always,
+            Some(&"never") => ColorChoice::Never,
+            // A bare `--color` or `--color auto` means auto; with no flag at
+            // all we default to never so piped output is clean.
+            Some(_) => ColorChoice::Auto,
+            None if flags.contains(&"--color") => ColorChoice::Auto,
+            None => ColorChoice::Never,
+        }
+    }
+
+    /// Resolve to a concrete yes/no, probing whether stdout is a TTY in `auto`.
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal(),
+        }
+    }
 }
 
 impl Flags {
@@ -34,71 +93,529 @@ impl Flags {
            invert_match: flags.contains(&"-v"),
            match_entire_line: flags.contains(&"-x"),
            invert: flags.contains(&"-v"),
+           fixed_string: flags.contains(&"-F"),
+           recursive: flags.contains(&"-r"),
+           count: flags.contains(&"-c"),
+           only_matching: flags.contains(&"-o"),
+           before: flag_value(flags, "-B").max(flag_value(flags, "-C")),
+           after: flag_value(flags, "-A").max(flag_value(flags, "-C")),
+           color: ColorChoice::new(flags),
        }
     }
 }
 
+/// Read the numeric argument that follows `name` in `flags` (e.g. the `3` in
+/// `-A 3`), defaulting to `0` when the flag is absent or its value doesn't
+/// parse. `-C` feeds both `before` and `after`, so it's folded in with `max`.
+fn flag_value(flags: &[&str], name: &str) -> usize {
+    flags
+        .iter()
+        .position(|&flag| flag == name)
+        .and_then(|i| flags.get(i + 1))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
 
 pub fn grep(pattern: &str, flags: &Flags, files: &[&str]) -> Result<Vec<String>, Error> {
+    grep_with_threads(pattern, flags, files, 0)
+}
+
+/// Like [`grep`], but caps the rayon worker count at `threads` so callers
+/// embedding this in a larger tool can bound resource use. A `threads` of `0`
+/// means "use rayon's global pool" (all available cores).
+///
+/// Files are searched in parallel, but each file collects its matches into its
+/// own `Vec<String>` which are then concatenated back in `files` order, so the
+/// output is byte-for-byte identical to a sequential walk: results appear in
+/// file order, and in line order within each file. The per-file `Result`s are
+/// collected with short-circuiting, so once a file fails its error is returned
+/// and the remaining files are abandoned. When several files fail, which of
+/// their errors is returned depends on scheduling, not on `files` order.
+pub fn grep_with_threads(
+    pattern: &str,
+    flags: &Flags,
+    files: &[&str],
+    threads: usize,
+) -> Result<Vec<String>, Error> {
+    // In the default mode the pattern is a regular expression, compiled once
+    // up front so we don't pay for it (or re-allocate lowercased copies of
+    // every line) inside the per-line loop. `-F` opts back into the literal
+    // substring behavior, so we skip compilation in that case.
+    let regex = compile_pattern(pattern, flags)?;
+
+    // Expand glob patterns and (with `-r`) directories into a concrete list of
+    // files before we search anything (sorted within each argument, arguments
+    // kept in the order given), so the parallel loop below never sees a
+    // directory.
+    let paths = resolve_files(files, flags)?;
+    let multi_file = paths.len() > 1;
+
+    // Resolve `--color` once (the TTY probe in `auto` is a syscall we don't want
+    // to repeat per line), then pass the decision down as a plain bool.
+    let color = flags.color.enabled();
+
+    let search = || -> Result<Vec<String>, Error> {
+        let per_file: Vec<Vec<String>> = paths
+            .par_iter()
+            .map(|path| grep_file(path, pattern, regex.as_ref(), flags, multi_file, color))
+            .collect::<Result<_, _>>()?;
+        Ok(per_file.into_iter().flatten().collect())
+    };
+
+    if threads == 0 {
+        search()
+    } else {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(Error::other)?;
+        pool.install(search)
+    }
+}
+
+/// Expand the caller-supplied patterns into the concrete files to search.
+///
+/// Each entry is treated as a shell-style glob (`*.rs`, `src/**/*.txt`); a
+/// pattern that matches nothing falls back to being treated as a literal path
+/// so non-glob arguments keep working. Directories are skipped unless `-r` is
+/// set, in which case every regular file beneath them is included. The paths
+/// expanded from a single glob or directory are sorted and de-duplicated so
+/// they're deterministic regardless of filesystem iteration order, but the
+/// caller's argument order is preserved (real grep lists files in the order
+/// given).
+fn resolve_files(files: &[&str], flags: &Flags) -> Result<Vec<PathBuf>, Error> {
+    // With no files at all, behave like `cat`-less grep and read standard input.
+    if files.is_empty() {
+        return Ok(vec![PathBuf::from("-")]);
+    }
+
+    let mut resolved = Vec::new();
+
+    for &pattern in files {
+        // `-` is the standard-input pseudo-file; it must never be globbed.
+        if pattern == "-" {
+            resolved.push(PathBuf::from("-"));
+            continue;
+        }
+
+        let entries = glob(pattern).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+        // Collect this argument's expansion separately so we can sort it without
+        // disturbing the relative order of the other arguments.
+        let mut expanded = Vec::new();
+        let mut matched = false;
+        for entry in entries {
+            let path = entry.map_err(Error::other)?;
+            matched = true;
+            collect_path(&path, flags, &mut expanded)?;
+        }
+
+        if !matched {
+            collect_path(Path::new(pattern), flags, &mut expanded)?;
+        }
+
+        expanded.sort();
+        expanded.dedup();
+        resolved.extend(expanded);
+    }
+
+    Ok(resolved)
+}
+
+/// Add a single resolved path to `out`, expanding directories when `-r` is set
+/// and dropping them otherwise.
+fn collect_path(path: &Path, flags: &Flags, out: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if path.is_dir() {
+        if flags.recursive {
+            let pattern = format!("{}/**/*", path.display());
+            let entries = glob(&pattern).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+            for entry in entries {
+                let sub = entry.map_err(Error::other)?;
+                if sub.is_file() {
+                    out.push(sub);
+                }
+            }
+        }
+    } else {
+        out.push(path.to_path_buf());
+    }
+
+    Ok(())
+}
+
+/// Open a line reader for `path`, reading from standard input when `path` is
+/// the `-` pseudo-file. Both sources are boxed as `dyn BufRead` so the line
+/// loop in [`grep_file`] doesn't care which one it's iterating.
+fn open_reader(path: &Path) -> Result<Box<dyn BufRead>, Error> {
+    if path == Path::new("-") {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+/// The label used for a path in multi-file output; standard input reads as
+/// `(standard input)` to match grep.
+fn display_name(path: &Path) -> String {
+    if path == Path::new("-") {
+        "(standard input)".to_string()
+    } else {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// Search a single file, returning its matches in line order. Factored out of
+/// [`grep_with_threads`] so each file can be processed on its own rayon task.
+fn grep_file(
+    path: &Path,
+    pattern: &str,
+    regex: Option<&Regex>,
+    flags: &Flags,
+    multi_file: bool,
+    color: bool,
+) -> Result<Vec<String>, Error> {
+    let reader = open_reader(path)?;
+    let file_name = display_name(path);
+
+    // Context mode (`-A`/`-B`/`-C`) has its own line loop; it only applies to
+    // the ordinary per-line output, not the `-c`/`-l` summary shapes.
+    if (flags.before > 0 || flags.after > 0) && !flags.count && !flags.print_file_names {
+        return grep_file_with_context(reader, &file_name, pattern, regex, flags, multi_file, color);
+    }
+
     let mut results = Vec::new();
+    let mut file_has_match = false;
+    let mut match_count = 0usize;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = index + 1;
 
-    for &file_name in files {
-        let file = File::open(file_name)?;
-        let reader = BufReader::new(file);
-
-        let mut file_has_match = false;
-        for (index, line) in reader.lines().enumerate() {
-            let line = line?;
-            let line_number = index + 1;
-        
-            if line_matches(&line, pattern, flags) {
-                if flags.print_file_names {
-                    if !file_has_match {
-                        results.push(file_name.to_string());
-                        file_has_match = true;
-                    }
-                } else {
-                    let result = format_result(file_name, line_number, &line, flags, files);
-                    results.push(result);
+        if let Some(spans) = line_matches(&line, pattern, regex, flags) {
+            match_count += 1;
+
+            // `-c` suppresses the usual per-line output; we only need the tally,
+            // which is emitted once after the loop.
+            if flags.count {
+                continue;
+            }
+
+            if flags.print_file_names {
+                if !file_has_match {
+                    results.push(file_name.to_string());
+                    file_has_match = true;
+                }
+            } else if flags.only_matching {
+                for span in &spans {
+                    let text = &line[span.clone()];
+                    // Route through `format_match` so the
+                    // filename / line-number prefixes get colored just like a
+                    // normal match line, with the whole matched substring itself
+                    // highlighted.
+                    let full = std::slice::from_ref(&(0..text.len()));
+                    results.push(format_match(&file_name, line_number, text, full, flags, multi_file, color));
                 }
+            } else {
+                let result = format_match(&file_name, line_number, &line, &spans, flags, multi_file, color);
+                results.push(result);
             }
         }
     }
 
+    if flags.count {
+        let summary = if multi_file {
+            format!("{}:{}", file_name, match_count)
+        } else {
+            match_count.to_string()
+        };
+        results.push(summary);
+    }
+
     Ok(results)
 }
 
-fn line_matches(line: &str, pattern: &str, flags: &Flags) -> bool {
-    let line = if flags.case_insensitive { line.to_lowercase() } else { line.to_string() };
-    let pattern = if flags.case_insensitive { pattern.to_lowercase() } else { pattern.to_string() };
+/// Build the [`Regex`] used for matching, honoring `-i` (case-insensitive via
+/// the inline `(?i)` flag) and `-x` (whole-line anchoring via `^…$`). Returns
+/// `Ok(None)` in `-F` mode, where matching falls back to literal substring
+/// comparison. A pattern that fails to compile surfaces as an
+/// [`ErrorKind::InvalidInput`] error.
+fn compile_pattern(pattern: &str, flags: &Flags) -> Result<Option<Regex>, Error> {
+    if flags.fixed_string {
+        return Ok(None);
+    }
 
-    let matches = if flags.match_entire_line {
-        line == pattern
+    let mut expr = String::new();
+    if flags.case_insensitive {
+        expr.push_str("(?i)");
+    }
+    if flags.match_entire_line {
+        // Group the user pattern so top-level alternation (`cat|dog`) anchors as
+        // a whole (`^(?:cat|dog)$`) rather than `^cat|dog$`.
+        expr.push_str("^(?:");
+        expr.push_str(pattern);
+        expr.push_str(")$");
     } else {
-        line.contains(&pattern)
-    };
+        expr.push_str(pattern);
+    }
+
+    let regex = Regex::new(&expr).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    Ok(Some(regex))
+}
+
+/// Decide whether `line` is a hit and, if so, where the pattern matched.
+///
+/// Returns `None` when the line should not be printed, and `Some(spans)` when
+/// it should — where `spans` are the byte ranges of each match within the line
+/// (used for `-o` and `--color`). Under `-v` a hit is a line that *doesn't*
+/// match, so the returned spans are empty.
+fn line_matches(line: &str, pattern: &str, regex: Option<&Regex>, flags: &Flags) -> Option<Vec<Range<usize>>> {
+    let spans = match_spans(line, pattern, regex, flags);
+    let matched = !spans.is_empty();
 
     if flags.invert_match {
-        !matches
+        if matched {
+            None
+        } else {
+            Some(Vec::new())
+        }
+    } else if matched {
+        Some(spans)
     } else {
-        matches
+        None
     }
 }
 
-fn format_result(file_name: &str, line_number: usize, line: &str, flags: &Flags, files: &[&str]) -> String {
-    if flags.print_file_names {
-        file_name.to_string()
-    } else if flags.line_numbers {
-        if files.len() > 1 {
-            format!("{}:{}:{}", file_name, line_number, line)
-        } else {
-            format!("{}:{}", line_number, line)
-        }
+/// Compute the byte ranges at which the pattern matches within `line`, before
+/// any `-v` inversion is applied. In regex mode this is each `find_iter` match;
+/// in `-F` mode it's each literal occurrence (respecting `-i` and `-x`).
+fn match_spans(line: &str, pattern: &str, regex: Option<&Regex>, flags: &Flags) -> Vec<Range<usize>> {
+    if let Some(regex) = regex {
+        return regex.find_iter(line).map(|m| m.start()..m.end()).collect();
+    }
+
+    let (haystack, needle) = if flags.case_insensitive {
+        (line.to_lowercase(), pattern.to_lowercase())
     } else {
-        if files.len() > 1 {
-            format!("{}:{}", file_name, line)
+        (line.to_string(), pattern.to_string())
+    };
+
+    if flags.match_entire_line {
+        return if haystack == needle {
+            let span = 0..line.len();
+            vec![span]
         } else {
-            line.to_string()
+            Vec::new()
+        };
+    }
+
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = haystack[start..].find(&needle) {
+        let begin = start + offset;
+        let end = begin + needle.len();
+        spans.push(begin..end);
+        start = end;
+    }
+    spans
+}
+
+/// Search a single reader, emitting `before`/`after` context lines around each
+/// match. Context lines are joined with `-` rather than `:`, and a `--` group
+/// separator is inserted between non-contiguous match blocks. A sliding ring
+/// buffer holds the last `before` lines; `after_remaining` counts down the
+/// trailing lines still owed after a match; and [`emit_line`] de-duplicates so
+/// overlapping windows never print a line twice.
+fn grep_file_with_context(
+    reader: Box<dyn BufRead>,
+    file_name: &str,
+    pattern: &str,
+    regex: Option<&Regex>,
+    flags: &Flags,
+    multi_file: bool,
+    color: bool,
+) -> Result<Vec<String>, Error> {
+    let mut results = Vec::new();
+    let mut before_buf: VecDeque<(usize, String)> = VecDeque::new();
+    let mut after_remaining = 0usize;
+    let mut last_emitted: Option<usize> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = index + 1;
+
+        if let Some(spans) = line_matches(&line, pattern, regex, flags) {
+            let buffered: Vec<(usize, String)> = before_buf.drain(..).collect();
+            for (ln, text) in buffered {
+                let formatted = format_context(file_name, ln, &text, flags, multi_file);
+                emit_line(&mut results, &mut last_emitted, ln, formatted);
+            }
+            let formatted = format_match(file_name, line_number, &line, &spans, flags, multi_file, color);
+            emit_line(&mut results, &mut last_emitted, line_number, formatted);
+            after_remaining = flags.after;
+        } else if after_remaining > 0 {
+            let formatted = format_context(file_name, line_number, &line, flags, multi_file);
+            emit_line(&mut results, &mut last_emitted, line_number, formatted);
+            after_remaining -= 1;
+        }
+
+        if flags.before > 0 {
+            before_buf.push_back((line_number, line));
+            while before_buf.len() > flags.before {
+                before_buf.pop_front();
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// Push `formatted` onto `results` unless `line_number` was already emitted
+/// (overlapping context windows), inserting a `--` separator when there's a
+/// gap since the previously emitted line.
+fn emit_line(
+    results: &mut Vec<String>,
+    last_emitted: &mut Option<usize>,
+    line_number: usize,
+    formatted: String,
+) {
+    if let Some(prev) = *last_emitted {
+        if line_number <= prev {
+            return;
+        }
+        if line_number > prev + 1 {
+            results.push("--".to_string());
         }
     }
+    *last_emitted = Some(line_number);
+    results.push(formatted);
+}
+
+/// ANSI escape for a matched span: bold red.
+const MATCH_COLOR: &str = "\x1b[1;31m";
+/// ANSI escape for the filename prefix: green.
+const FILE_COLOR: &str = "\x1b[32m";
+/// ANSI escape for the line-number prefix: yellow.
+const LINE_COLOR: &str = "\x1b[33m";
+/// ANSI reset.
+const RESET: &str = "\x1b[0m";
+
+/// Wrap `text` in `code`…reset.
+fn paint(text: &str, code: &str) -> String {
+    format!("{}{}{}", code, text, RESET)
+}
+
+/// Rebuild `line` with each span in `spans` wrapped in the match color, or
+/// return it unchanged when `color` is off. Spans are non-overlapping and in
+/// left-to-right order, as produced by [`match_spans`].
+fn maybe_highlight(line: &str, spans: &[Range<usize>], color: bool) -> String {
+    if !color || spans.is_empty() {
+        return line.to_string();
+    }
+
+    let mut out = String::new();
+    let mut last = 0;
+    for span in spans {
+        out.push_str(&line[last..span.start]);
+        out.push_str(&paint(&line[span.clone()], MATCH_COLOR));
+        last = span.end;
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// Format a matching line, highlighting the matched spans and the filename /
+/// line-number prefixes when `color` is set.
+fn format_match(
+    file_name: &str,
+    line_number: usize,
+    line: &str,
+    spans: &[Range<usize>],
+    flags: &Flags,
+    multi_file: bool,
+    color: bool,
+) -> String {
+    let body = maybe_highlight(line, spans, color);
+    let name = if color { paint(file_name, FILE_COLOR) } else { file_name.to_string() };
+    let number = if color { paint(&line_number.to_string(), LINE_COLOR) } else { line_number.to_string() };
+
+    match (multi_file, flags.line_numbers) {
+        (true, true) => format!("{}:{}:{}", name, number, body),
+        (true, false) => format!("{}:{}", name, body),
+        (false, true) => format!("{}:{}", number, body),
+        (false, false) => body,
+    }
+}
+
+/// Format a context line (`-A`/`-B`/`-C`), using `-` as the field separator
+/// instead of the `:` used for matching lines.
+fn format_context(file_name: &str, line_number: usize, line: &str, flags: &Flags, multi_file: bool) -> String {
+    match (multi_file, flags.line_numbers) {
+        (true, true) => format!("{}-{}-{}", file_name, line_number, line),
+        (true, false) => format!("{}-{}", file_name, line),
+        (false, true) => format!("{}-{}", line_number, line),
+        (false, false) => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Write `contents` to a uniquely-named temp file and return its path.
+    fn temp_file(tag: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("grep_test_{}_{}", std::process::id(), tag));
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn preserves_file_argument_order() {
+        // Matches must appear in the order the files were given, not sorted.
+        let first = temp_file("first.txt", "beta\n");
+        let second = temp_file("second.txt", "alpha\n");
+
+        let flags = Flags::new(&[]);
+        let out = grep(
+            "a",
+            &flags,
+            &[second.to_str().unwrap(), first.to_str().unwrap()],
+        )
+        .unwrap();
+
+        std::fs::remove_file(&first).ok();
+        std::fs::remove_file(&second).ok();
+
+        assert_eq!(out.len(), 2);
+        assert!(out[0].ends_with(":alpha"), "{:?}", out);
+        assert!(out[1].ends_with(":beta"), "{:?}", out);
+    }
+
+    /// Run the context loop over an in-memory reader with `-C N`.
+    fn context(input: &str, context: &str) -> Vec<String> {
+        let flags = Flags::new(&["-C", context]);
+        let regex = compile_pattern("MATCH", &flags).unwrap();
+        let reader: Box<dyn BufRead> = Box::new(io::Cursor::new(input.to_string()));
+        grep_file_with_context(reader, "in", "MATCH", regex.as_ref(), &flags, false, false).unwrap()
+    }
+
+    #[test]
+    fn context_windows_dedup_overlapping_lines() {
+        // Matches on lines 3 and 5 with -C 1: their context windows overlap on
+        // line 4, which must appear exactly once and not repeat.
+        let out = context("a\nb\nMATCH\nd\nMATCH\nf\ng\n", "1");
+        assert_eq!(out, vec!["b", "MATCH", "d", "MATCH", "f"]);
+    }
+
+    #[test]
+    fn context_inserts_group_separator_between_blocks() {
+        // Matches on lines 1 and 6 with -C 1 are non-contiguous, so a `--`
+        // separator divides the two blocks.
+        let out = context("MATCH\nb\nc\nd\ne\nMATCH\n", "1");
+        assert_eq!(out, vec!["MATCH", "b", "--", "e", "MATCH"]);
+    }
 }
\ No newline at end of file